@@ -27,10 +27,11 @@ use futures::{StreamExt, Future};
 use tokio::io::AsyncRead;
 use std::io::{Error, ErrorKind};
 use std::mem::replace;
+use std::marker::PhantomData;
 use std::pin::Pin;
 use std::task::{Poll, Context};
 
-pub async fn read_headers<T: AsyncRead + Unpin>(rdr: &mut PacketReader<T>) -> Result<HeaderSet, VorbisError> {
+pub async fn read_headers<T: AsyncRead + Unpin>(rdr: &mut PacketReader<T>) -> Result<(HeaderSet, u32), VorbisError> {
     macro_rules! rd_pck {
         () => {
             match rdr.next().await.transpose()? {
@@ -43,12 +44,14 @@ pub async fn read_headers<T: AsyncRead + Unpin>(rdr: &mut PacketReader<T>) -> Re
         }
     }
 
-    let ident = read_header_ident(&rd_pck!().data)?;
+    let ident_pck = rd_pck!();
+    let stream_serial = ident_pck.stream_serial();
+    let ident = read_header_ident(&ident_pck.data)?;
     let comment = read_header_comment(&rd_pck!().data)?;
     let setup = read_header_setup(&rd_pck!().data,
         ident.audio_channels, (ident.blocksize_0, ident.blocksize_1))?;
 
-    Ok((ident, comment, setup))
+    Ok(((ident, comment, setup), stream_serial))
 }
 
 /// Async ready creator utility to read headers out of an
@@ -59,6 +62,7 @@ pub struct HeadersReader<T: AsyncRead + Unpin> {
     pck_rd :PacketReader<T>,
     ident_hdr :Option<IdentHeader>,
     comment_hdr :Option<CommentHeader>,
+    stream_serial :Option<u32>,
 }
 impl<T: AsyncRead + Unpin> HeadersReader<T> {
     pub fn new(inner :T) -> Self {
@@ -69,8 +73,16 @@ impl<T: AsyncRead + Unpin> HeadersReader<T> {
             pck_rd,
             ident_hdr : None,
             comment_hdr : None,
+            stream_serial : None,
         }
     }
+    /// Returns the stream serial of the headers that were read.
+    ///
+    /// Returns `Some` as soon as the ident header packet has been read,
+    /// which is always the case once this future has resolved successfully.
+    pub fn stream_serial(&self) -> Option<u32> {
+        self.stream_serial
+    }
 }
 impl<T: AsyncRead + Unpin> Future for HeadersReader<T> {
     type Output = Result<HeaderSet, VorbisError>;
@@ -94,6 +106,7 @@ impl<T: AsyncRead + Unpin> Future for HeadersReader<T> {
         }
         if self.ident_hdr.is_none() {
             let pck = rd_pck!();
+            self.stream_serial = Some(pck.stream_serial());
             self.ident_hdr = Some(read_header_ident(&pck.data)?);
         }
         if self.comment_hdr.is_none() {
@@ -111,6 +124,21 @@ impl<T: AsyncRead + Unpin> Future for HeadersReader<T> {
         Poll::Ready(Ok((ident_hdr, comment_hdr, setup_hdr)))
     }
 }
+/// State of an in-progress transition to a new logical bitstream,
+/// encountered mid-stream in a chained ogg file.
+///
+/// Re-reading the three header packets of the new bitstream can take
+/// several calls to `poll_next` if the underlying reader returns
+/// `Poll::Pending` along the way, so the progress made so far is
+/// kept here instead of on the stack.
+enum ChainState {
+    /// Not currently transitioning to a new logical bitstream.
+    Idle,
+    GotIdent(IdentHeader),
+    GotIdentComment(IdentHeader, CommentHeader),
+    GotHeaders(IdentHeader, CommentHeader, SetupHeader),
+}
+
 /// Reading ogg/vorbis files or streams
 ///
 /// This is a small helper struct to help reading ogg/vorbis files
@@ -122,33 +150,204 @@ impl<T: AsyncRead + Unpin> Future for HeadersReader<T> {
 ///
 /// If you need support for this, you need to use the lower level methods
 /// instead.
+///
+/// Seeking isn't supported on this async reader, as the underlying
+/// async `PacketReader` doesn't offer seek support at the moment.
+/// Once it grows one, this struct can gain a `seek_absgp` wrapping it,
+/// analogous to the sync `OggStreamReader`'s `seek_absgp_pg`, bound on
+/// `T: AsyncRead + Seek + Unpin`.
 pub struct OggStreamReader<T :AsyncRead + Unpin> {
     pck_rd :PacketReader<T>,
     pwr :PreviousWindowRight,
 
+    stream_serial :u32,
+
     pub ident_hdr :IdentHeader,
     pub comment_hdr :CommentHeader,
     pub setup_hdr :SetupHeader,
 
-    absgp_of_last_read :Option<u64>,
+    cur_absgp :Option<u64>,
+
+    chain_state :ChainState,
 }
 
 impl<T :AsyncRead + Unpin> OggStreamReader<T> {
     /// Creates a new OggStreamReader from the given parameters
     pub fn new(hdr_rdr :HeadersReader<T>, hdrs :HeaderSet) -> Self {
-        OggStreamReader::from_pck_rdr(hdr_rdr.pck_rd, hdrs)
+        let stream_serial = hdr_rdr.stream_serial()
+            .expect("HeadersReader must be polled to completion before being passed to OggStreamReader::new");
+        OggStreamReader::from_pck_rdr(hdr_rdr.pck_rd, hdrs, stream_serial)
     }
-    /// Creates a new OggStreamReader from the given parameters
-    pub fn from_pck_rdr(pck_rd :PacketReader<T>, hdrs :HeaderSet) -> Self {
+    /// Creates a new OggStreamReader from the given parameters, with
+    /// the stream serial of the headers that were already read out of
+    /// `pck_rd` (as returned by the free `read_headers` function).
+    ///
+    /// Knowing the stream serial up front lets `poll_next` tell a
+    /// chained ogg file's new logical bitstream apart from the one
+    /// whose headers were just read.
+    pub fn from_pck_rdr(pck_rd :PacketReader<T>, hdrs :HeaderSet, stream_serial :u32) -> Self {
         OggStreamReader {
             pck_rd,
             pwr : PreviousWindowRight::new(),
 
+            stream_serial,
+
             ident_hdr : hdrs.0,
             comment_hdr : hdrs.1,
             setup_hdr : hdrs.2,
 
-            absgp_of_last_read : None,
+            cur_absgp : None,
+
+            chain_state : ChainState::Idle,
+        }
+    }
+
+    /// Returns the absolute granule position of the last read page.
+    ///
+    /// In the case of ogg/vorbis, the absolute granule position is given
+    /// as number of PCM samples, on a per channel basis.
+    pub fn get_last_absgp(&self) -> Option<u64> {
+        self.cur_absgp
+    }
+
+    // Makes progress on an in-progress transition to a new logical
+    // bitstream, mirroring what the sync reader's
+    // `read_next_audio_packet` does for chained ogg files, just
+    // spread out over however many `poll_next` calls it takes for the
+    // underlying reader to hand us the three header packets and the
+    // priming audio packet.
+    //
+    // Returns `Ready(Ok(true))` once the transition is complete and
+    // the reader is ready to yield the first real audio packet of the
+    // new bitstream on the next loop iteration, or `Ready(Ok(false))`
+    // if the physical stream ended in the middle of the transition.
+    fn poll_advance_chain(&mut self, cx: &mut Context) -> Poll<Result<bool, VorbisError>> {
+        loop {
+            match self.chain_state {
+                ChainState::Idle => return Poll::Ready(Ok(true)),
+                ChainState::GotIdent(_) => {
+                    let pck = match ready!(Pin::new(&mut self.pck_rd).poll_next(cx)?) {
+                        Some(p) => p,
+                        None => return Poll::Ready(Ok(false)),
+                    };
+                    let comment_hdr = match read_header_comment(&pck.data) {
+                        Ok(h) => h,
+                        Err(e) => return Poll::Ready(Err(e.into())),
+                    };
+                    let ident_hdr = match replace(&mut self.chain_state, ChainState::Idle) {
+                        ChainState::GotIdent(h) => h,
+                        _ => unreachable!(),
+                    };
+                    self.chain_state = ChainState::GotIdentComment(ident_hdr, comment_hdr);
+                },
+                ChainState::GotIdentComment(..) => {
+                    let pck = match ready!(Pin::new(&mut self.pck_rd).poll_next(cx)?) {
+                        Some(p) => p,
+                        None => return Poll::Ready(Ok(false)),
+                    };
+                    let (ident_hdr, comment_hdr) = match replace(&mut self.chain_state, ChainState::Idle) {
+                        ChainState::GotIdentComment(i, c) => (i, c),
+                        _ => unreachable!(),
+                    };
+                    let setup_hdr = match read_header_setup(&pck.data,
+                            ident_hdr.audio_channels, (ident_hdr.blocksize_0, ident_hdr.blocksize_1)) {
+                        Ok(h) => h,
+                        Err(e) => return Poll::Ready(Err(e.into())),
+                    };
+                    self.chain_state = ChainState::GotHeaders(ident_hdr, comment_hdr, setup_hdr);
+                },
+                ChainState::GotHeaders(..) => {
+                    let pck = match ready!(Pin::new(&mut self.pck_rd).poll_next(cx)?) {
+                        Some(p) => p,
+                        None => return Poll::Ready(Ok(false)),
+                    };
+                    let (ident_hdr, comment_hdr, setup_hdr) = match replace(&mut self.chain_state, ChainState::Idle) {
+                        ChainState::GotHeaders(i, c, s) => (i, c, s),
+                        _ => unreachable!(),
+                    };
+                    // Prime the decoder's previous window right state
+                    // with the first audio packet of the new logical
+                    // bitstream, discarding its decoded output, same
+                    // as the sync reader does.
+                    let mut pwr = PreviousWindowRight::new();
+                    let _decoded_pck :Vec<Vec<i16>> = match read_audio_packet(&ident_hdr,
+                            &setup_hdr, &pck.data, &mut pwr) {
+                        Ok(p) => p,
+                        Err(e) => return Poll::Ready(Err(e.into())),
+                    };
+                    self.pwr = pwr;
+                    self.stream_serial = pck.stream_serial();
+                    self.ident_hdr = ident_hdr;
+                    self.comment_hdr = comment_hdr;
+                    self.setup_hdr = setup_hdr;
+                    self.cur_absgp = Some(pck.absgp_page());
+                    return Poll::Ready(Ok(true));
+                },
+            }
+        }
+    }
+}
+
+impl<T :AsyncRead + Unpin> OggStreamReader<T> {
+    /// Reads and decompresses an audio packet from the stream (generic).
+    ///
+    /// This is the async, poll based equivalent of the sync reader's
+    /// `read_dec_packet_generic`, generalized over the `Samples` trait
+    /// so that callers can obtain e.g. interleaved or floating point
+    /// samples directly from the decode path, without a separate
+    /// post-processing pass.
+    pub fn poll_next_generic<S :Samples>(self: Pin<&mut Self>, cx: &mut Context)
+            -> Poll<Option<Result<S, VorbisError>>> {
+        let this = self.get_mut();
+        loop {
+            if !matches!(this.chain_state, ChainState::Idle) {
+                match this.poll_advance_chain(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Ok(false)) => return Poll::Ready(None),
+                    Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                    Poll::Ready(Ok(true)) => {},
+                }
+            }
+            let pck = match ready!(Pin::new(&mut this.pck_rd).poll_next(cx)?) {
+                Some(p) => p,
+                None => return Poll::Ready(None),
+            };
+            if pck.stream_serial() != this.stream_serial {
+                if pck.first_in_stream() {
+                    // We have a chained ogg file. This means we need
+                    // to re-initialize the internal context, which
+                    // poll_advance_chain takes care of.
+                    let ident_hdr = match read_header_ident(&pck.data) {
+                        Ok(h) => h,
+                        Err(e) => return Poll::Ready(Some(Err(e.into()))),
+                    };
+                    this.chain_state = ChainState::GotIdent(ident_hdr);
+                } else {
+                    // Ignore every packet that has a mismatching stream serial
+                }
+                continue;
+            }
+            let mut decoded_pck :S = match read_audio_packet_generic(&this.ident_hdr,
+                    &this.setup_hdr, &pck.data, &mut this.pwr) {
+                Ok(p) => p,
+                Err(e) => return Poll::Ready(Some(Err(e.into()))),
+            };
+
+            // If this is the last packet in the logical bitstream,
+            // we need to truncate it so that its ending matches
+            // the absgp of the current page.
+            // This is what the spec mandates and also the behaviour
+            // of libvorbis.
+            if let (Some(absgp), true) = (this.cur_absgp, pck.last_in_stream()) {
+                let target_length = pck.absgp_page().saturating_sub(absgp) as usize;
+                decoded_pck.truncate(target_length);
+            }
+            if pck.last_in_page() {
+                this.cur_absgp = Some(pck.absgp_page());
+            } else if let Some(ref mut absgp) = this.cur_absgp {
+                *absgp += decoded_pck.num_samples() as u64;
+            }
+            return Poll::Ready(Some(Ok(decoded_pck)));
         }
     }
 }
@@ -156,15 +355,47 @@ impl<T :AsyncRead + Unpin> OggStreamReader<T> {
 impl<T :AsyncRead + Unpin> Stream for OggStreamReader<T> {
     type Item = Result<Vec<Vec<i16>>, VorbisError>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        let this = &mut *self;
-        let pck = match ready!(Pin::new(&mut this.pck_rd).poll_next(cx)?) {
-            Some(p) => p,
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.poll_next_generic(cx)
+    }
+}
+
+/// Stream adapter that yields interleaved samples instead of the
+/// per-channel planar samples `OggStreamReader` itself produces.
+///
+/// This is the async equivalent of the sync reader's
+/// `read_dec_packet_itl`, useful for feeding audio sinks that expect
+/// interleaved buffers, the common case for real-time playback.
+///
+/// Generic over the sample type `S` (e.g. `i16` or `f32`), so the same
+/// adapter serves interleaved integer and floating point output; pick
+/// the concrete type via e.g. `InterleavedOggStreamReader<T, i16>`.
+pub struct InterleavedOggStreamReader<T :AsyncRead + Unpin, S> {
+    inner :OggStreamReader<T>,
+    _sample_ty :PhantomData<S>,
+}
+
+impl<T :AsyncRead + Unpin, S> InterleavedOggStreamReader<T, S> {
+    pub fn new(inner :OggStreamReader<T>) -> Self {
+        InterleavedOggStreamReader { inner, _sample_ty : PhantomData }
+    }
+    pub fn into_inner(self) -> OggStreamReader<T> {
+        self.inner
+    }
+}
+
+impl<T :AsyncRead + Unpin, S> Stream for InterleavedOggStreamReader<T, S>
+        where InterleavedSamples<S> :Samples {
+    type Item = Result<Vec<S>, VorbisError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let decoded_pck :InterleavedSamples<S> =
+                match ready!(Pin::new(&mut this.inner).poll_next_generic(cx)) {
+            Some(Ok(p)) => p,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
             None => return Poll::Ready(None),
         };
-        let decoded_pck = read_audio_packet(&this.ident_hdr,
-            &this.setup_hdr, &pck.data, &mut this.pwr)?;
-        self.absgp_of_last_read = Some(pck.absgp_page());
-        Poll::Ready(Some(Ok(decoded_pck)))
+        Poll::Ready(Some(Ok(decoded_pck.samples)))
     }
 }
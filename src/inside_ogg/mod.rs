@@ -16,5 +16,8 @@ and useful helper methods for the Ogg `PacketReader` struct.
 mod sync;
 pub use sync::*;
 
+mod passthrough;
+pub use passthrough::*;
+
 #[cfg(feature = "async_ogg")]
 pub mod async_api;
@@ -0,0 +1,223 @@
+// Vorbis decoder written in Rust
+//
+// Copyright (c) 2016 est31 <MTest31@outlook.com>
+// and contributors. All rights reserved.
+// Licensed under MIT license, or Apache 2 license,
+// at your option. Please see the LICENSE file
+// attached to this source distribution for details.
+
+use ogg::{Packet, PacketReader, PacketWriter, PacketWriteEndInfo};
+use crate::VorbisError;
+use std::io::{self, Read, Seek, Write};
+use std::sync::{Arc, Mutex};
+
+// A cheap, clonable `Write` sink that all `PacketWriter` instances we
+// create write into, so that we can pull the bytes they produced back
+// out after every call without tearing the writer down.
+//
+// This uses `Arc<Mutex<_>>` rather than `Rc<RefCell<_>>` so that
+// `PassthroughReader` stays `Send`, like every other reader in this
+// module; there's no actual concurrent access happening, since both
+// handles are only ever touched from `PassthroughReader`'s own methods.
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuf {
+	fn write(&mut self, buf :&[u8]) -> io::Result<usize> {
+		self.0.lock().unwrap().extend_from_slice(buf);
+		Ok(buf.len())
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl SharedBuf {
+	fn take(&self) -> Option<Box<[u8]>> {
+		let mut buf = self.0.lock().unwrap();
+		if buf.is_empty() {
+			return None;
+		}
+		Some(std::mem::take(&mut *buf).into_boxed_slice())
+	}
+}
+
+/**
+Remuxes an ogg/vorbis stream without decoding it
+
+This is a small helper struct that lets a caller pull the three
+header packets and all audio packets of an ogg/vorbis stream out in
+raw, undecoded form, and re-muxes them into a fresh, self-contained
+ogg stream.
+
+As the audio packets are never decoded, this is useful for proxying
+or relaying a vorbis stream at close to zero CPU cost, for example
+to a downstream player that does the decoding itself.
+
+Like `OggStreamReader`, it only supports the main use case of pure
+audio ogg files or streams.
+*/
+pub struct PassthroughReader<T: Read + Seek> {
+	rdr :PacketReader<T>,
+
+	stream_serial :u32,
+	next_out_serial :u32,
+
+	ident_pck :Box<[u8]>,
+	comment_pck :Box<[u8]>,
+	setup_pck :Box<[u8]>,
+
+	buf :SharedBuf,
+	wtr :PacketWriter<SharedBuf>,
+
+	// Set whenever the output needs to start a new logical stream,
+	// either because we just started, or because the caller seeked.
+	needs_headers :bool,
+	// The absgp of the audio packets is rebased against this value,
+	// so that a freshly started logical stream always begins at
+	// granule position zero (relative to wherever we last seeked to).
+	absgp_rebase :u64,
+	// Set after a seek, until the first post-seek packet is written,
+	// at which point `absgp_rebase` is set to that packet's actual
+	// page absgp (the seek only guarantees landing on a page whose
+	// absgp is <= the requested one, so the real landing position has
+	// to be read off the page rather than assumed to be the request).
+	needs_absgp_rebase_capture :bool,
+}
+
+impl<T: Read + Seek> PassthroughReader<T> {
+	/// Constructs a new PassthroughReader from a given implementation of `Read + Seek`.
+	pub fn new(rdr :T) -> Result<Self, VorbisError> {
+		PassthroughReader::from_ogg_reader(PacketReader::new(rdr))
+	}
+	/// Constructs a new PassthroughReader from a given Ogg PacketReader.
+	///
+	/// The `new` function is a nice wrapper around this function that
+	/// also creates the ogg reader.
+	pub fn from_ogg_reader(mut rdr :PacketReader<T>) -> Result<Self, VorbisError> {
+		let pck :Packet = rdr.read_packet_expected()?;
+		let stream_serial = pck.stream_serial();
+		let ident_pck = pck.data.into_boxed_slice();
+
+		let mut pck :Packet = rdr.read_packet_expected()?;
+		while pck.stream_serial() != stream_serial {
+			pck = rdr.read_packet_expected()?;
+		}
+		let comment_pck = pck.data.into_boxed_slice();
+
+		let mut pck :Packet = rdr.read_packet_expected()?;
+		while pck.stream_serial() != stream_serial {
+			pck = rdr.read_packet_expected()?;
+		}
+		let setup_pck = pck.data.into_boxed_slice();
+
+		rdr.delete_unread_packets();
+
+		let buf = SharedBuf::default();
+		let wtr = PacketWriter::new(buf.clone());
+
+		Ok(PassthroughReader {
+			rdr,
+			stream_serial,
+			next_out_serial : stream_serial,
+			ident_pck,
+			comment_pck,
+			setup_pck,
+			buf,
+			wtr,
+			needs_headers : true,
+			absgp_rebase : 0,
+			needs_absgp_rebase_capture : false,
+		})
+	}
+	pub fn into_inner(self) -> PacketReader<T> {
+		self.rdr
+	}
+
+	// Writes the three header packets into the output, allocating a
+	// fresh stream serial, and flushing a page boundary after the
+	// ident and setup packets, as players expect for header framing.
+	fn write_headers(&mut self) -> Result<(), VorbisError> {
+		let serial = self.next_out_serial;
+		self.wtr.write_packet(self.ident_pck.clone(), serial,
+			PacketWriteEndInfo::EndPage, 0)?;
+		self.wtr.write_packet(self.comment_pck.clone(), serial,
+			PacketWriteEndInfo::NormalPacket, 0)?;
+		self.wtr.write_packet(self.setup_pck.clone(), serial,
+			PacketWriteEndInfo::EndPage, 0)?;
+		self.next_out_serial = self.next_out_serial.wrapping_add(1);
+		self.needs_headers = false;
+		Ok(())
+	}
+
+	/// Reads a packet of raw, re-muxed ogg bytes from the stream.
+	///
+	/// On read errors, it returns `Err(e)` with the error.
+	///
+	/// On success, it either returns `None`, when the end of the
+	/// stream has been reached, or `Some(ogg_bytes)`, with the
+	/// re-muxed ogg bytes obtained from the packet(s) that were just
+	/// read. As most packets don't flush a page on their own, this
+	/// can consume several input packets before any output bytes
+	/// become available; callers should keep calling `read_packet`
+	/// in a `while let Some(bytes) = read_packet()?` loop rather than
+	/// treating one call as one input packet.
+	pub fn read_packet(&mut self) -> Result<Option<Box<[u8]>>, VorbisError> {
+		if self.needs_headers {
+			self.write_headers()?;
+			if let Some(out) = self.buf.take() {
+				return Ok(Some(out));
+			}
+		}
+		loop {
+			let pck = match self.rdr.read_packet()? {
+				Some(p) => p,
+				None => return Ok(self.buf.take()),
+			};
+			if pck.stream_serial() != self.stream_serial {
+				// Ignore every packet that has a mismatching stream serial
+				continue;
+			}
+			if self.needs_absgp_rebase_capture {
+				self.absgp_rebase = pck.absgp_page();
+				self.needs_absgp_rebase_capture = false;
+			}
+			let absgp = pck.absgp_page().saturating_sub(self.absgp_rebase);
+			let end_info = if pck.last_in_stream() {
+				PacketWriteEndInfo::EndStream
+			} else if pck.last_in_page() {
+				PacketWriteEndInfo::EndPage
+			} else {
+				PacketWriteEndInfo::NormalPacket
+			};
+			self.wtr.write_packet(pck.data.into_boxed_slice(),
+				self.next_out_serial.wrapping_sub(1), end_info, absgp)?;
+			if let Some(out) = self.buf.take() {
+				return Ok(Some(out));
+			}
+			// This packet didn't flush a page; keep pulling packets
+			// until one does, or we run out of input.
+		}
+	}
+
+	/// Seeks to the specified absolute granule position, with a page granularity,
+	/// re-starting the output as a brand new logical ogg stream.
+	///
+	/// The granularity is per-page, and the obtained position is
+	/// then <= the seeked absgp.
+	///
+	/// Most players reject an ogg stream whose first audio page has a
+	/// non-monotonic or large granule offset, so after a seek we
+	/// re-emit the three header packets with a fresh stream serial,
+	/// and rebase the granule positions of the following audio
+	/// packets so that they start counting from the actual landing
+	/// position of the seek (captured off the first post-seek page,
+	/// since the seek is only guaranteed to land on a page whose
+	/// absgp is <= the requested one).
+	pub fn seek_absgp_pg(&mut self, absgp :u64) -> Result<(), VorbisError> {
+		self.rdr.seek_absgp(None, absgp)?;
+		self.needs_headers = true;
+		self.needs_absgp_rebase_capture = true;
+		Ok(())
+	}
+}
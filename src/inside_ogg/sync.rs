@@ -10,6 +10,7 @@ use ogg::{PacketReader, Packet};
 use crate::header::*;
 use crate::VorbisError;
 use std::io::{Read, Seek};
+use std::time::Duration;
 use crate::audio::{PreviousWindowRight, read_audio_packet,
 	read_audio_packet_generic};
 use crate::header::HeaderSet;
@@ -243,4 +244,45 @@ impl<T: Read + Seek> OggStreamReader<T> {
 		self.pwr = PreviousWindowRight::new();
 		Ok(())
 	}
+
+	/// Seeks to the specified time, with a page granularity.
+	///
+	/// The given `time` is converted to an absolute granule position
+	/// using the sample rate of the stream, and the seek is then
+	/// carried out via `seek_absgp_pg`, with the same page granularity
+	/// guarantees that method has.
+	pub fn seek_time(&mut self, time :Duration) -> Result<(), VorbisError> {
+		let sample_rate = self.ident_hdr.audio_sample_rate as u64;
+		// Use 64 bit intermediate math, split into a whole-seconds and
+		// a sub-second part, so that we don't overflow for long
+		// durations, and round to the nearest sample instead of
+		// truncating.
+		let whole_secs_samples = time.as_secs() * sample_rate;
+		let subsec_samples = (time.subsec_nanos() as u64 * sample_rate
+			+ 500_000_000) / 1_000_000_000;
+		let absgp = whole_secs_samples + subsec_samples;
+		self.seek_absgp_pg(absgp)
+	}
+
+	/// Seeks to the specified time, given in milliseconds, with a page granularity.
+	///
+	/// This is a convenience wrapper around `seek_time`.
+	pub fn seek_ms(&mut self, ms :u64) -> Result<(), VorbisError> {
+		self.seek_time(Duration::from_millis(ms))
+	}
+
+	/// Returns the current playback position as a `Duration`.
+	///
+	/// This is the counterpart to `seek_time`/`seek_ms`, mapping
+	/// `get_last_absgp` back to a `Duration` using the sample rate of
+	/// the stream. Returns `None` if no absolute granule position is
+	/// known yet.
+	pub fn current_time(&self) -> Option<Duration> {
+		let absgp = self.get_last_absgp()?;
+		let sample_rate = self.ident_hdr.audio_sample_rate as u64;
+		let secs = absgp / sample_rate;
+		let rem_samples = absgp % sample_rate;
+		let nanos = rem_samples * 1_000_000_000 / sample_rate;
+		Some(Duration::new(secs, nanos as u32))
+	}
 }